@@ -1,15 +1,213 @@
-use anyhow::Result;
+use crate::imapsession::AuthMethod;
+use crate::oauth::Provider;
+use crate::secret_store::SecretBackend;
+use anyhow::{Result, anyhow};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs;
 use std::path::PathBuf;
+use url::Url;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Config {
+    /// "gmail" (default) or "microsoft"
+    pub provider: Option<Provider>,
+    /// "oauth" (default) or "password" (plain LOGIN, password kept in the keyring)
+    pub auth_method: Option<AuthMethod>,
     pub client_id: String,
     /// optional: client secret can be stored in keyring; better keep it out of the file
     pub imap_server: Option<String>,
+    /// defaults to 993 (implicit TLS), or 143 when use_starttls is set
+    pub imap_port: Option<u16>,
+    /// connect in plaintext and upgrade with STARTTLS instead of implicit TLS
+    pub use_starttls: Option<bool>,
+    /// seconds to wait for the IMAP server (connect, TLS handshake, each read/write)
+    /// before giving up; default 30
+    pub imap_timeout_secs: Option<u64>,
+    /// account address; with auth_method = "password" it's the LOGIN username and
+    /// needn't be an email address
     pub user_email: Option<String>,
     pub redirect_uri: Option<String>,
+    /// use the OAuth device-code flow instead of a browser + loopback redirect
+    pub device_code: Option<bool>,
+    /// OAuth scopes to request instead of the provider default (full mail access).
+    /// Narrower scopes such as Gmail's `gmail.readonly` are not accepted for IMAP, and
+    /// the stored refresh token keeps its old scopes until you sign in again.
+    pub scopes: Option<Vec<String>>,
+    /// refresh the access token this many seconds before it expires (default 60)
+    pub refresh_skew_secs: Option<i64>,
+    /// mailbox to open, e.g. "INBOX" or "[Gmail]/Sent Mail" (defaults to INBOX)
+    pub mailbox: Option<String>,
+    /// how many of the newest messages to list (default 10, must be at least 1)
+    pub page_size: Option<u32>,
+    /// "keyring" (default) or "file": an encrypted secrets.enc next to this config,
+    /// unlocked with RS_MAIL_CLIENT_PASSPHRASE, for machines without a keyring service
+    pub secret_store: Option<SecretBackend>,
+    /// name of the account in `accounts` used when `--account` isn't given
+    pub default_account: Option<String>,
+    /// `[[accounts]]` entries for multiple mailboxes; unset per-account fields
+    /// fall back to the top-level values above
+    pub accounts: Option<Vec<Account>>,
+    /// `[keys]` section: action name -> key, e.g. `move_down = "n"` (see terminal::keymap)
+    pub keys: Option<HashMap<String, String>>,
+}
+
+/// One mail account in a multi-account config
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Account {
+    pub name: String,
+    pub user_email: String,
+    pub provider: Option<Provider>,
+    pub auth_method: Option<AuthMethod>,
+    pub client_id: Option<String>,
+    /// replaces the top-level scopes; also dropped when `provider` differs from the
+    /// top-level one, since those scopes were meant for the other provider
+    pub scopes: Option<Vec<String>>,
+    pub imap_server: Option<String>,
+    pub imap_port: Option<u16>,
+    pub use_starttls: Option<bool>,
+    pub mailbox: Option<String>,
+}
+
+impl Config {
+    /// Resolve the effective config for one account. `name` (or `default_account`,
+    /// or else the first entry) is matched against account names and emails, and that
+    /// account's fields override the top-level ones. Without `accounts` the config is
+    /// returned unchanged.
+    pub fn for_account(&self, name: Option<&str>) -> Result<Config> {
+        let accounts = self.accounts.as_deref().unwrap_or_default();
+        let wanted = name.or(self.default_account.as_deref());
+        let account = match wanted {
+            Some(w) => accounts
+                .iter()
+                .find(|a| a.name == w || a.user_email == w)
+                .ok_or_else(|| anyhow!("no account named '{}' in config", w))?,
+            None => match accounts.first() {
+                Some(a) => a,
+                None => return Ok(self.clone()),
+            },
+        };
+
+        let mut cfg = self.clone();
+        cfg.user_email = Some(account.user_email.clone());
+        if let Some(provider) = account.provider {
+            if provider != self.provider.unwrap_or_default() {
+                cfg.scopes = None;
+            }
+            cfg.provider = Some(provider);
+        }
+        if let Some(auth_method) = account.auth_method {
+            cfg.auth_method = Some(auth_method);
+        }
+        if let Some(client_id) = &account.client_id {
+            cfg.client_id = client_id.clone();
+        }
+        if let Some(scopes) = &account.scopes {
+            cfg.scopes = Some(scopes.clone());
+        }
+        if let Some(server) = &account.imap_server {
+            cfg.imap_server = Some(server.clone());
+        }
+        if let Some(starttls) = account.use_starttls {
+            cfg.use_starttls = Some(starttls);
+            // a top-level port was picked for the top-level TLS mode; let it follow this one
+            cfg.imap_port = None;
+        }
+        if let Some(port) = account.imap_port {
+            cfg.imap_port = Some(port);
+        }
+        if let Some(mailbox) = &account.mailbox {
+            cfg.mailbox = Some(mailbox.clone());
+        }
+        Ok(cfg)
+    }
+
+    /// Catch unedited template values and obviously broken settings up front, so they
+    /// fail here with a pointer to the config file instead of deep inside OAuth/IMAP.
+    pub fn validate(&self) -> Result<()> {
+        let mut problems = Vec::new();
+
+        let oauth = self.auth_method.unwrap_or_default() == AuthMethod::OAuth;
+        if oauth {
+            if self.client_id.trim().is_empty() {
+                problems.push("client_id is empty".to_string());
+            } else if self.client_id.starts_with("YOUR_CLIENT_ID") {
+                problems.push(
+                    "client_id is still the template placeholder; set your OAuth client id"
+                        .to_string(),
+                );
+            }
+            if self.device_code == Some(true) {
+                let provider = self.provider.unwrap_or_default();
+                let scope = self
+                    .scopes
+                    .as_ref()
+                    .map(|s| s.join(" "))
+                    .unwrap_or_else(|| provider.default_scope().to_string());
+                if let Some(blocked) = provider.device_code_blocks(&scope) {
+                    problems.push(format!(
+                        "device_code = true can't be used with Gmail: Google's device-code flow \
+                         doesn't allow the {} scope IMAP needs; set device_code = false to sign \
+                         in through the browser (PKCE)",
+                        blocked
+                    ));
+                }
+            }
+            if let Some(uri) = &self.redirect_uri
+                && let Err(e) = Url::parse(uri)
+            {
+                problems.push(format!("redirect_uri '{}' is not a valid URL: {}", uri, e));
+            }
+        }
+
+        if self.scopes.as_ref().is_some_and(|s| s.is_empty()) {
+            problems.push("scopes is empty; remove it to use the provider default".to_string());
+        }
+
+        match self.user_email.as_deref() {
+            None => problems.push("user_email is not set".to_string()),
+            Some("you@example.com") => problems
+                .push("user_email is still the template placeholder 'you@example.com'".to_string()),
+            // with password auth this is the LOGIN username, which for self-hosted
+            // servers is often a bare local name ("andrea") or user@localhost
+            Some(_) if !oauth => {}
+            Some(email) => {
+                let plausible = email
+                    .split_once('@')
+                    .is_some_and(|(local, domain)| !local.is_empty() && domain.contains('.'));
+                if !plausible {
+                    problems.push(format!(
+                        "user_email '{}' doesn't look like an email address",
+                        email
+                    ));
+                }
+            }
+        }
+
+        if self.refresh_skew_secs.is_some_and(|s| s < 0) {
+            problems.push("refresh_skew_secs must not be negative".to_string());
+        }
+
+        if self.imap_timeout_secs == Some(0) {
+            problems.push("imap_timeout_secs must be at least 1".to_string());
+        }
+
+        if self.page_size == Some(0) {
+            problems.push("page_size must be at least 1".to_string());
+        }
+
+        if problems.is_empty() {
+            return Ok(());
+        }
+        let path = config_path()
+            .map(|p| p.display().to_string())
+            .unwrap_or_else(|_| "config.toml".to_string());
+        Err(anyhow!(
+            "please fix {}:\n  - {}",
+            path,
+            problems.join("\n  - ")
+        ))
+    }
 }
 
 fn config_dir() -> Result<PathBuf> {
@@ -30,10 +228,25 @@ pub fn load_config() -> Result<Config> {
     if !path.exists() {
         // create a template config for users to edit
         let sample = Config {
+            provider: Some(Provider::Gmail),
+            auth_method: Some(AuthMethod::OAuth),
             client_id: "YOUR_CLIENT_ID.apps.googleusercontent.com".to_string(),
             imap_server: Some("imap.gmail.com".to_string()),
+            // left unset so the port follows use_starttls (993, or 143 with STARTTLS)
+            imap_port: None,
+            use_starttls: Some(false),
+            imap_timeout_secs: Some(30),
             user_email: Some("you@example.com".to_string()),
             redirect_uri: Some("http://127.0.0.1:8080/callback".to_string()),
+            device_code: Some(false),
+            scopes: None,
+            refresh_skew_secs: Some(60),
+            mailbox: Some("INBOX".to_string()),
+            page_size: Some(10),
+            secret_store: Some(SecretBackend::Keyring),
+            default_account: None,
+            accounts: None,
+            keys: None,
         };
         let tom = toml::to_string_pretty(&sample)?;
         fs::write(&path, tom)?;
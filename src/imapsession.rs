@@ -1,86 +1,458 @@
 use crate::decoders::decode_subject;
+use crate::xoauth2::OAuth2Authenticator;
 use anyhow::Result;
-use base64::{Engine as _, engine::general_purpose};
-use native_tls::TlsConnector;
-
-/// Build canonical auth string as bytes:
-fn build_xoauth2_bytes(user: &str, access_token: &str) -> Vec<u8> {
-    let user_field = format!("user={}", user);
-    let auth_field = format!("auth=Bearer {}", access_token);
-    // three fields joined by SOH, plus final SOH so there are two trailing SOHs
-    let auth_string = format!("{}{}{}{}{}", user_field, "\x01", auth_field, "\x01", "\x01");
-    auth_string.into_bytes()
+use imap_proto::types::Capability;
+use log::{debug, info, warn};
+use native_tls::{HandshakeError, TlsConnector};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::fmt;
+use std::io::ErrorKind;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+/// The server rejected our XOAUTH2 credentials (as opposed to a network/TLS failure)
+#[derive(Debug)]
+pub struct AuthRejected(pub String);
+
+impl fmt::Display for AuthRejected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "IMAP authentication rejected: {}", self.0)
+    }
 }
 
-struct OAuth2Authenticator {
-    response: Vec<u8>,
+impl std::error::Error for AuthRejected {}
+
+/// True if `err` means the access token was refused, so refreshing it may help
+pub fn is_auth_error(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<AuthRejected>().is_some()
 }
 
-impl imap::Authenticator for OAuth2Authenticator {
-    type Response = Vec<u8>;
-    fn process(&self, _challenge: &[u8]) -> Self::Response {
-        self.response.clone()
-    }
+type ImapSession = imap::Session<native_tls::TlsStream<TcpStream>>;
+
+/// Default for how long connecting, the TLS handshake and each read/write may block
+pub const DEFAULT_TIMEOUT_SECS: u64 = 30;
+
+/// How to authenticate to the IMAP server
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum AuthMethod {
+    /// SASL XOAUTH2 with an OAuth access token
+    #[default]
+    OAuth,
+    /// plain LOGIN with a password (only sensible over TLS)
+    Password,
 }
 
-pub fn list_recent_subjects(imap_server: &str, user_email: &str, access_token: &str) -> Result<()> {
-    println!("Connecting to {}:993", imap_server);
-    let tls = TlsConnector::builder().build()?;
-    let mut client = imap::connect((imap_server, 993), imap_server, &tls)?;
+/// Where and how to reach the IMAP server for one account
+pub struct ImapClient {
+    server: String,
+    port: u16,
+    starttls: bool,
+    user_email: String,
+    auth: AuthMethod,
+    timeout: Duration,
+    /// capabilities from the last successful login, recorded by `connect_and_auth`
+    capabilities: RefCell<Option<Vec<String>>>,
+}
 
-    // Build the canonical payload bytes
-    let raw_payload = build_xoauth2_bytes(user_email, access_token);
+impl ImapClient {
+    /// `starttls = false` means implicit TLS (usually port 993); `true` connects in
+    /// plaintext and upgrades with STARTTLS (usually port 143)
+    pub fn new(server: &str, port: u16, starttls: bool, user_email: &str) -> Self {
+        ImapClient {
+            server: server.to_string(),
+            port,
+            starttls,
+            user_email: user_email.to_string(),
+            auth: AuthMethod::OAuth,
+            timeout: Duration::from_secs(DEFAULT_TIMEOUT_SECS),
+            capabilities: RefCell::new(None),
+        }
+    }
 
-    // Try RAW first (many imap crate versions expect library to base64-encode)
-    println!("Trying XOAUTH2 using RAW response (no base64)...");
-    let auth_raw = OAuth2Authenticator {
-        response: raw_payload.clone(),
-    };
-    match client.authenticate("XOAUTH2", &auth_raw) {
-        Ok(mut session) => {
-            println!("Authenticated with RAW response!");
-            dump_inbox(&mut session)?;
-            session.logout()?;
-            return Ok(());
+    /// Choose how `connect_and_auth` logs in. The `access_token` argument of the
+    /// public methods carries the password when `AuthMethod::Password` is set.
+    pub fn with_auth(mut self, auth: AuthMethod) -> Self {
+        self.auth = auth;
+        self
+    }
+
+    /// Give up on a dead network after `timeout` instead of hanging; applies to the TCP
+    /// connect and to every read and write on the socket afterwards
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Open the TCP connection, trying each resolved address within the timeout
+    fn connect_tcp(&self) -> Result<TcpStream> {
+        let mut last_err = None;
+        for addr in (self.server.as_str(), self.port).to_socket_addrs()? {
+            match TcpStream::connect_timeout(&addr, self.timeout) {
+                Ok(tcp) => {
+                    tcp.set_read_timeout(Some(self.timeout))?;
+                    tcp.set_write_timeout(Some(self.timeout))?;
+                    return Ok(tcp);
+                }
+                Err(e) => last_err = Some(e),
+            }
         }
-        Err((e, returned_client)) => {
-            eprintln!("RAW attempt failed: {}", e);
-            // put the client back so we can try again
-            client = returned_client;
+        Err(match last_err {
+            Some(e) => imap::Error::Io(e).into(),
+            None => anyhow::anyhow!("{} did not resolve to any address", self.server),
+        })
+    }
+
+    /// Connect over TLS, authenticate, and record the capabilities the server
+    /// advertises to the logged-in session (see `has_capability`)
+    fn connect_and_auth(&self, access_token: &str) -> Result<ImapSession> {
+        let tls = TlsConnector::builder().build()?;
+        let server = self.server.as_str();
+        let (client, greeting_caps) = if self.starttls {
+            info!("Connecting to {}:{} (STARTTLS)", server, self.port);
+            let mut plain = imap::Client::new(self.connect_tcp()?);
+            plain.read_greeting()?;
+            // capabilities seen before the TLS upgrade can't be trusted, so ignore them
+            (plain.secure(server, &tls)?, None)
+        } else {
+            info!("Connecting to {}:{}", server, self.port);
+            let stream = tls
+                .connect(server, self.connect_tcp()?)
+                .map_err(imap::Error::TlsHandshake)?;
+            let mut client = imap::Client::new(stream);
+            let greeting = client.read_greeting()?;
+            (client, greeting_capabilities(&greeting))
+        };
+
+        let mut session = self.authenticate(client, greeting_caps.as_deref(), access_token)?;
+        let caps = capability_names(&mut session)?;
+        debug!("Server capabilities: {}", caps.join(" "));
+        *self.capabilities.borrow_mut() = Some(caps);
+        Ok(session)
+    }
+
+    /// Log in with XOAUTH2 (RAW first, BASE64 as fallback), or with LOGIN when password
+    /// auth is configured. `greeting_caps` are the capabilities from the server greeting,
+    /// if it sent any; when they lack AUTH=XOAUTH2 we fail early instead of trying it.
+    fn authenticate(
+        &self,
+        mut client: imap::Client<native_tls::TlsStream<TcpStream>>,
+        greeting_caps: Option<&[String]>,
+        access_token: &str,
+    ) -> Result<ImapSession> {
+        if self.auth == AuthMethod::Password {
+            info!("Logging in as {} with password...", self.user_email);
+            return client
+                .login(&self.user_email, access_token)
+                .map_err(|(e, _client)| auth_failure(e, "LOGIN failed"));
+        }
+
+        if let Some(caps) = greeting_caps
+            && !caps.iter().any(|c| c.eq_ignore_ascii_case("AUTH=XOAUTH2"))
+        {
+            // not AuthRejected: a fresh token wouldn't change what the server supports
+            return Err(anyhow::anyhow!(
+                "{} does not offer AUTH=XOAUTH2; use auth_method = \"password\" for this server",
+                self.server
+            ));
+        }
+
+        // Try RAW first (many imap crate versions expect library to base64-encode)
+        debug!("Trying XOAUTH2 using RAW response (no base64)...");
+        let auth_raw = OAuth2Authenticator::raw(&self.user_email, access_token);
+        match client.authenticate("XOAUTH2", &auth_raw) {
+            Ok(session) => {
+                debug!("Authenticated with RAW response!");
+                return Ok(session);
+            }
+            Err((e, returned_client)) if is_rejection(&e) => {
+                warn!("RAW attempt failed: {}", e);
+                // put the client back so we can try again
+                client = returned_client;
+            }
+            // the connection itself failed; retrying on it won't help
+            Err((e, _returned_client)) => return Err(e.into()),
+        }
+
+        // Try BASE64 (server canonical form) as fallback
+        let auth_b64 = OAuth2Authenticator::base64(&self.user_email, access_token);
+        debug!(
+            "Trying XOAUTH2 using BASE64 response (len {})...",
+            auth_b64.response_len()
+        );
+        match client.authenticate("XOAUTH2", &auth_b64) {
+            Ok(session) => {
+                debug!("Authenticated with BASE64 response!");
+                Ok(session)
+            }
+            Err((e, _returned_client)) => Err(auth_failure(
+                e,
+                "both RAW and BASE64 XOAUTH2 attempts failed; last error",
+            )),
         }
     }
 
-    // Try BASE64 (server canonical form) as fallback
-    let b64_bytes = general_purpose::STANDARD.encode(&raw_payload).into_bytes();
-    println!(
-        "Trying XOAUTH2 using BASE64 response (len {})...",
-        b64_bytes.len()
-    );
-    let auth_b64 = OAuth2Authenticator {
-        response: b64_bytes.clone(),
-    };
-    match client.authenticate("XOAUTH2", &auth_b64) {
-        Ok(mut session) => {
-            println!("Authenticated with BASE64 response!");
-            dump_inbox(&mut session)?;
-            session.logout()?;
-            Ok(())
+    /// Whether the server advertised `name` (e.g. "IDLE", "AUTH=XOAUTH2") on the most
+    /// recent connection; false if we haven't connected yet
+    pub fn has_capability(&self, name: &str) -> bool {
+        self.capabilities
+            .borrow()
+            .as_ref()
+            .is_some_and(|caps| caps.iter().any(|c| c.eq_ignore_ascii_case(name)))
+    }
+
+    /// Run `op` on a freshly authenticated session and log out afterwards. If the
+    /// server drops the connection mid-command (idle timeout, BYE, reset), reconnect
+    /// and re-authenticate once before failing; protocol errors are not retried.
+    fn with_session<T>(
+        &self,
+        access_token: &str,
+        op: impl Fn(&mut ImapSession) -> Result<T>,
+    ) -> Result<T> {
+        let run = || -> Result<T> {
+            let mut session = self.connect_and_auth(access_token)?;
+            let out = op(&mut session)?;
+            // the work is done; a server hanging up on LOGOUT must not trigger a rerun
+            if let Err(e) = session.logout() {
+                debug!("IMAP LOGOUT failed after a successful command: {}", e);
+            }
+            Ok(out)
+        };
+        let result = match run() {
+            Err(e) if is_connection_dropped(&e) => {
+                warn!("IMAP connection dropped ({}); reconnecting once", e);
+                run()
+            }
+            result => result,
+        };
+        result.map_err(|e| {
+            if is_timed_out(&e) {
+                anyhow::anyhow!(
+                    "IMAP connection timed out ({}:{}, no response within {}s)",
+                    self.server,
+                    self.port,
+                    self.timeout.as_secs()
+                )
+            } else {
+                e
+            }
+        })
+    }
+
+    /// Print the subjects of the newest `page_size` messages in `mailbox`
+    pub fn list_recent_subjects(
+        &self,
+        access_token: &str,
+        mailbox: &str,
+        page_size: u32,
+    ) -> Result<()> {
+        self.with_session(access_token, |session| {
+            dump_mailbox(session, mailbox, page_size)
+        })
+    }
+
+    /// Capabilities advertised by the server after login (e.g. "IDLE", "MOVE")
+    pub fn capabilities(&self, access_token: &str) -> Result<Vec<String>> {
+        // connecting records them; no need to ask twice
+        self.with_session(access_token, |_session| Ok(()))?;
+        Ok(self.capabilities.borrow().clone().unwrap_or_default())
+    }
+
+    /// List all mailbox (folder / label) names visible to the user
+    pub fn list_mailboxes(&self, access_token: &str) -> Result<Vec<String>> {
+        self.with_session(access_token, |session| {
+            let names = session.list(None, Some("*"))?;
+            Ok(names.iter().map(|n| n.name().to_string()).collect())
+        })
+    }
+
+    /// Run a server-side UID SEARCH in `mailbox` and return (uid, decoded subject) pairs,
+    /// oldest first. `criteria` must already be valid IMAP syntax (see `build_search_criteria`).
+    pub fn search(
+        &self,
+        access_token: &str,
+        mailbox: &str,
+        criteria: &str,
+    ) -> Result<Vec<(u32, String)>> {
+        self.with_session(access_token, |session| {
+            session.select(mailbox)?;
+
+            let mut uids: Vec<u32> = session.uid_search(criteria)?.into_iter().collect();
+            uids.sort_unstable();
+
+            let mut results = Vec::with_capacity(uids.len());
+            if !uids.is_empty() {
+                let set = uids
+                    .iter()
+                    .map(|u| u.to_string())
+                    .collect::<Vec<_>>()
+                    .join(",");
+                let messages = session.uid_fetch(set, "ENVELOPE")?;
+                for msg in messages.iter() {
+                    let Some(uid) = msg.uid else {
+                        continue;
+                    };
+                    let subject = msg
+                        .envelope()
+                        .and_then(|env| env.subject)
+                        .map(decode_subject)
+                        .unwrap_or_else(|| "(none)".to_string());
+                    results.push((uid, subject));
+                }
+                results.sort_by_key(|(uid, _)| *uid);
+            }
+            Ok(results)
+        })
+    }
+}
+
+/// Capability names as the server spelled them, via the typed CAPABILITY response,
+/// sorted and deduplicated
+fn capability_names(session: &mut ImapSession) -> Result<Vec<String>> {
+    let caps = session.capabilities()?;
+    let mut names: Vec<String> = caps
+        .iter()
+        .map(|cap| match cap {
+            Capability::Imap4rev1 => "IMAP4rev1".to_string(),
+            Capability::Auth(mech) => format!("AUTH={}", mech),
+            Capability::Atom(atom) => atom.to_string(),
+        })
+        .collect();
+    names.sort();
+    names.dedup();
+    Ok(names)
+}
+
+/// Capabilities from a `[CAPABILITY ...]` response code in the server greeting, if the
+/// server included one (many do, so we can see the SASL mechanisms before logging in)
+fn greeting_capabilities(greeting: &[u8]) -> Option<Vec<String>> {
+    let text = String::from_utf8_lossy(greeting);
+    let start = text.find("[CAPABILITY ")? + "[CAPABILITY ".len();
+    let len = text[start..].find(']')?;
+    Some(
+        text[start..start + len]
+            .split_whitespace()
+            .map(str::to_string)
+            .collect(),
+    )
+}
+
+/// True if the server answered the login/AUTHENTICATE with NO or BAD, i.e. it looked
+/// at the credentials and refused them
+fn is_rejection(err: &imap::Error) -> bool {
+    matches!(err, imap::Error::No(_) | imap::Error::Bad(_))
+}
+
+/// Wrap server rejections as `AuthRejected` (worth a token refresh); network, TLS and
+/// other errors pass through unchanged so `with_session` can reconnect on them
+fn auth_failure(err: imap::Error, context: &str) -> anyhow::Error {
+    if is_rejection(&err) {
+        AuthRejected(format!("{}: {}", context, err)).into()
+    } else {
+        err.into()
+    }
+}
+
+/// True if `err` means the server closed the connection under us, as opposed to
+/// rejecting a command; only these are worth a transparent reconnect.
+fn is_connection_dropped(err: &anyhow::Error) -> bool {
+    match err.downcast_ref::<imap::Error>() {
+        Some(imap::Error::ConnectionLost) => true,
+        Some(imap::Error::Io(e)) => matches!(
+            e.kind(),
+            ErrorKind::ConnectionReset
+                | ErrorKind::ConnectionAborted
+                | ErrorKind::BrokenPipe
+                | ErrorKind::UnexpectedEof
+        ),
+        _ => false,
+    }
+}
+
+/// True if `err` comes from a socket timeout. A read that hits the timeout shows up
+/// as `WouldBlock` on Unix and `TimedOut` on Windows; during the TLS handshake it
+/// surfaces as an interrupted (`WouldBlock`) handshake.
+fn is_timed_out(err: &anyhow::Error) -> bool {
+    let io_timeout =
+        |e: &std::io::Error| matches!(e.kind(), ErrorKind::TimedOut | ErrorKind::WouldBlock);
+    match err.downcast_ref::<imap::Error>() {
+        Some(imap::Error::Io(e)) => io_timeout(e),
+        Some(imap::Error::TlsHandshake(HandshakeError::WouldBlock(_))) => true,
+        _ => false,
+    }
+}
+
+/// Translate a simple user query into IMAP SEARCH criteria.
+///
+/// Supported terms: `from:x`, `to:x`, `subject:x`, `since:1-Jan-2024`,
+/// `before:1-Jan-2024`; any other word becomes a `TEXT` match. An empty query
+/// matches everything (`ALL`).
+///
+/// Every user-supplied value is emitted as an IMAP quoted string via `imap_quote`,
+/// so a query can never inject raw protocol (extra criteria, a new command line, ...).
+/// Quoted strings are 7-bit only, so non-ASCII terms are refused with an error rather
+/// than sent raw for the server to reject with BAD.
+pub fn build_search_criteria(query: &str) -> Result<String> {
+    let mut criteria = Vec::new();
+    for word in query.split_whitespace() {
+        let (key, value) = match word.split_once(':') {
+            Some((k, v)) if !v.is_empty() => match k.to_ascii_lowercase().as_str() {
+                "from" => ("FROM", v),
+                "to" => ("TO", v),
+                "subject" => ("SUBJECT", v),
+                "since" => ("SINCE", v),
+                "before" => ("BEFORE", v),
+                _ => ("TEXT", word),
+            },
+            _ => ("TEXT", word),
+        };
+        criteria.push(format!("{} {}", key, imap_quote(value)?));
+    }
+    if criteria.is_empty() {
+        Ok("ALL".to_string())
+    } else {
+        Ok(criteria.join(" "))
+    }
+}
+
+/// Quote a value as an IMAP quoted string (RFC 3501 `quoted`).
+/// Backslashes and double quotes are backslash-escaped; CR, LF and NUL are not
+/// allowed inside a quoted string at all, so they are dropped. Only 7-bit text is
+/// allowed there; anything else would need a literal, which we can't send.
+fn imap_quote(value: &str) -> Result<String> {
+    if !value.is_ascii() {
+        return Err(anyhow::anyhow!(
+            "search term '{}' contains non-ASCII characters, which IMAP search can't \
+             take here; search for an ASCII part of it instead",
+            value
+        ));
+    }
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' | '\\' => {
+                out.push('\\');
+                out.push(c);
+            }
+            '\r' | '\n' | '\0' => {}
+            _ => out.push(c),
         }
-        Err((e, _returned_client)) => Err(anyhow::anyhow!(
-            "Both RAW and BASE64 XOAUTH2 attempts failed; last error: {}",
-            e
-        )),
     }
+    out.push('"');
+    Ok(out)
 }
 
-fn dump_inbox(
-    session: &mut imap::Session<native_tls::TlsStream<std::net::TcpStream>>,
-) -> Result<()> {
-    let mailbox = session.select("INBOX")?;
-    println!("INBOX has {} messages", mailbox.exists);
+fn dump_mailbox(session: &mut ImapSession, name: &str, page_size: u32) -> Result<()> {
+    let mailbox = session.select(name)?;
+    println!("{} has {} messages", name, mailbox.exists);
+    if mailbox.exists == 0 {
+        return Ok(());
+    }
 
-    let start = if mailbox.exists > 10 {
-        mailbox.exists - 9
+    let start = if mailbox.exists > page_size {
+        mailbox.exists - page_size + 1
     } else {
         1
     };
@@ -98,3 +470,49 @@ fn dump_inbox(
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quotes_and_backslashes_are_escaped() {
+        assert_eq!(imap_quote(r#"a"b\c"#).unwrap(), r#""a\"b\\c""#);
+    }
+
+    #[test]
+    fn line_breaks_and_nul_are_stripped() {
+        assert_eq!(imap_quote("x\r\nA1 LOGOUT\0").unwrap(), r#""xA1 LOGOUT""#);
+        // whitespace splits the query first, so a CRLF can't start a new command there either
+        assert_eq!(
+            build_search_criteria("subject:x\r\nA1").unwrap(),
+            r#"SUBJECT "x" TEXT "A1""#
+        );
+    }
+
+    #[test]
+    fn known_keys_map_to_search_keys() {
+        assert_eq!(
+            build_search_criteria("from:alice since:1-Jan-2024").unwrap(),
+            r#"FROM "alice" SINCE "1-Jan-2024""#
+        );
+    }
+
+    #[test]
+    fn unknown_key_becomes_text() {
+        assert_eq!(
+            build_search_criteria("foo:bar").unwrap(),
+            r#"TEXT "foo:bar""#
+        );
+    }
+
+    #[test]
+    fn empty_query_matches_all() {
+        assert_eq!(build_search_criteria("   ").unwrap(), "ALL");
+    }
+
+    #[test]
+    fn non_ascii_is_refused() {
+        assert!(build_search_criteria("subject:café").is_err());
+    }
+}
@@ -2,21 +2,33 @@ mod config;
 mod decoders;
 mod imapsession;
 mod oauth;
+mod secret_store;
 mod terminal;
 mod token_store;
 mod tokens_file;
+mod xoauth2;
 
 use anyhow::{Result, anyhow};
+use imapsession::{AuthMethod, ImapClient};
+use log::{debug, info, warn};
 use std::io::Read;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use terminal::keymap::KeyMap;
 use terminal::run_terminal;
 
 fn main() -> Result<()> {
-    env_logger::init();
+    let args = std::env::args().collect::<Vec<_>>();
+    init_logging(&args)?;
+
+    // Try to load config (creates a template if missing). The secret backend is
+    // chosen first because the --set-* utilities below already store secrets.
+    let loaded = config::load_config();
+    if let Ok(c) = &loaded {
+        token_store::set_backend(c.secret_store.unwrap_or_default())?;
+    }
 
-    // CLI utility: set client secret into keyring:
+    // CLI utility: set client secret into the secret store:
     // Usage: rs_mail_client --set-client-secret <client_id>
-    let args = std::env::args().collect::<Vec<_>>();
     if args.len() >= 2 && args[1] == "--set-client-secret" {
         if args.len() < 3 {
             eprintln!("Usage: --set-client-secret <client_id>");
@@ -32,12 +44,65 @@ fn main() -> Result<()> {
         return Ok(());
     }
 
-    // Try to load config (creates a template if missing)
-    let cfg = match config::load_config() {
+    // CLI utility: set IMAP password into the secret store (for auth_method = "password"):
+    // Usage: rs_mail_client --set-password <email>
+    if args.len() >= 2 && args[1] == "--set-password" {
+        if args.len() < 3 {
+            eprintln!("Usage: --set-password <email>");
+            return Ok(());
+        }
+        let user = args[2].clone();
+        eprintln!("Paste IMAP password (end with Ctrl-D):");
+        let mut password = String::new();
+        std::io::stdin().read_to_string(&mut password)?;
+        token_store::save_password(&user, password.trim_end_matches(['\r', '\n']))?;
+        println!("Saved IMAP password for {}", user);
+        return Ok(());
+    }
+
+    let cfg = match loaded {
         Ok(c) => c,
         Err(e) => return Err(anyhow!("Configuration error: {}", e)),
     };
 
+    // The pre-accounts tokens.json belongs to the top-level user_email; give it to that
+    // account before --account picks one, so no other account ever reads it
+    if let Some(owner) = &cfg.user_email {
+        tokens_file::migrate_legacy_tokens(owner)?;
+    }
+
+    // Pick the account to use when the config lists several
+    // Usage: rs_mail_client --account <name|email>
+    let account = args
+        .iter()
+        .position(|a| a == "--account")
+        .and_then(|pos| args.get(pos + 1))
+        .map(String::as_str);
+    let cfg = cfg
+        .for_account(account)
+        .map_err(|e| anyhow!("Configuration error: {}", e))?;
+    cfg.validate()
+        .map_err(|e| anyhow!("Configuration error: {}", e))?;
+
+    // Validate key bindings up front so a typo fails now rather than inside the TUI
+    let keymap = KeyMap::from_config(cfg.keys.as_ref())
+        .map_err(|e| anyhow!("Configuration error: {}", e))?;
+
+    let provider = cfg.provider.unwrap_or_default();
+    let scope = cfg
+        .scopes
+        .as_ref()
+        .map(|s| s.join(" "))
+        .unwrap_or_else(|| provider.default_scope().to_string());
+    if cfg.auth_method.unwrap_or_default() == AuthMethod::OAuth
+        && !scope.split_whitespace().any(|s| s == provider.imap_scope())
+    {
+        // e.g. gmail.readonly: the consent screen accepts it, but IMAP login won't
+        warn!(
+            "configured scopes don't include {}; the server will reject IMAP login with these tokens",
+            provider.imap_scope()
+        );
+    }
     let client_id = cfg.client_id.clone();
     let redirect = cfg
         .redirect_uri
@@ -46,156 +111,384 @@ fn main() -> Result<()> {
     let imap_server = cfg
         .imap_server
         .clone()
-        .unwrap_or_else(|| "imap.gmail.com".to_string());
+        .unwrap_or_else(|| provider.default_imap_server().to_string());
+    let use_starttls = cfg.use_starttls.unwrap_or(false);
+    let imap_port = cfg
+        .imap_port
+        .unwrap_or(if use_starttls { 143 } else { 993 });
+    let imap_timeout = Duration::from_secs(
+        cfg.imap_timeout_secs
+            .unwrap_or(imapsession::DEFAULT_TIMEOUT_SECS),
+    );
+    let mailbox = cfg.mailbox.clone().unwrap_or_else(|| "INBOX".to_string());
+    let page_size = cfg.page_size.unwrap_or(10);
     let user_email = cfg
         .user_email
         .clone()
         .ok_or_else(|| anyhow!("user_email not set in config"))?;
 
+    // Sign out: revoke the refresh token and forget every stored token for the account
+    // Usage: rs_mail_client --logout [--account <name|email>]
+    if args.iter().any(|a| a == "--logout") {
+        return logout(provider, &client_id, &user_email);
+    }
+
+    // Password auth skips OAuth entirely; the password plays the access token's role
+    if cfg.auth_method.unwrap_or_default() == AuthMethod::Password {
+        let password = token_store::load_password(&user_email)?.ok_or_else(|| {
+            anyhow!(
+                "auth_method is \"password\" but no password is stored for {}; run --set-password {}",
+                user_email,
+                user_email
+            )
+        })?;
+        let imap = ImapClient::new(&imap_server, imap_port, use_starttls, &user_email)
+            .with_timeout(imap_timeout)
+            .with_auth(AuthMethod::Password);
+        let no_refresh = || -> Result<oauth::Tokens> {
+            Err(anyhow!(
+                "IMAP rejected the stored password; update it with --set-password {}",
+                user_email
+            ))
+        };
+        return run_commands(
+            &args, &imap, &mailbox, page_size, password, no_refresh, keymap,
+        );
+    }
+
     // Try to load secrets from keyring (client secret optional)
     let client_secret = token_store::load_client_secret(&client_id)?
         .or_else(|| std::env::var("OAUTH_CLIENT_SECRET").ok());
 
+    // Interactive auth: browser + loopback redirect (PKCE), or device code on headless machines
+    // Usage: rs_mail_client --device-code (or set device_code = true in config)
+    let use_device_code =
+        args.iter().any(|a| a == "--device-code") || cfg.device_code.unwrap_or(false);
+    let interactive_auth = || {
+        if use_device_code {
+            oauth::perform_device_code_flow(
+                provider,
+                &client_id,
+                client_secret.as_deref(),
+                &scope,
+                &user_email,
+            )
+        } else {
+            oauth::perform_pkce_flow(
+                provider,
+                &client_id,
+                client_secret.as_deref(),
+                &redirect,
+                &scope,
+                &user_email,
+            )
+        }
+    };
+
     // Try to load refresh token from keyring
     let refresh_token = token_store::load_refresh_token(&user_email)?;
 
-    // Try to load cached access token + expiry
-    let cached = tokens_file::load_tokens()?;
+    // Try to load cached access token expiry
+    let cached = tokens_file::load_tokens(&user_email)?;
+
+    // Older versions kept the access token in plaintext in tokens.json:
+    // move it into the keyring and blank the file field.
+    if let Some(tf) = &cached
+        && let Some(at) = &tf.access_token
+    {
+        token_store::save_access_token(&user_email, at)?;
+        tokens_file::save_tokens(&user_email, tf.expires_at_epoch)?;
+        info!("Moved cached access token from tokens file into keyring");
+    }
+
+    // Try to load cached access token from keyring
+    let cached_access = token_store::load_access_token(&user_email)?;
     let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+    let skew = cfg
+        .refresh_skew_secs
+        .unwrap_or(tokens_file::DEFAULT_REFRESH_SKEW_SECS);
 
     // Decide what to do:
-    // 1) If cached access_token exists and not expired -> use it.
+    // 1) If cached access_token exists and not (about to be) expired -> use it.
     // 2) Else if refresh_token exists -> refresh.
-    // 3) Else -> interactive PKCE flow.
+    // 3) Else -> interactive flow (PKCE or device code).
     let tokens = if let Some(tf) = cached {
-        if let (Some(at), Some(exp)) = (tf.access_token, tf.expires_at_epoch) {
-            if now < exp {
-                println!("Using cached access token (not expired).");
+        if let (Some(at), Some(exp)) = (cached_access, tf.expires_at_epoch) {
+            if !tokens_file::is_expired(exp, now, skew) {
+                info!("Using cached access token (not expired).");
                 oauth::Tokens {
                     access_token: at,
                     refresh_token: None,
-                    expires_in: Some((exp - now) as u64),
+                    expires_in: Some(exp.saturating_sub(now).max(0) as u64),
                 }
             } else {
                 // expired: try refresh if possible
                 if let Some(rt) = refresh_token.clone() {
-                    println!("Cached token expired; refreshing with refresh token...");
-                    match oauth::refresh_access_token(&client_id, client_secret.as_deref(), &rt) {
+                    info!("Cached token expired; refreshing with refresh token...");
+                    match oauth::refresh_access_token(
+                        provider,
+                        &client_id,
+                        client_secret.as_deref(),
+                        &rt,
+                    ) {
                         Ok(t) => t,
                         Err(e) => {
-                            eprintln!("Refresh failed: {}, falling back to interactive auth", e);
-                            oauth::perform_pkce_flow(
-                                &client_id,
-                                client_secret.as_deref(),
-                                &redirect,
-                                "https://mail.google.com/",
-                                &user_email,
-                            )?
+                            warn!("Refresh failed: {}, falling back to interactive auth", e);
+                            interactive_auth()?
                         }
                     }
                 } else {
-                    println!(
-                        "Cached token expired and no refresh token; running interactive PKCE auth flow..."
+                    info!(
+                        "Cached token expired and no refresh token; running interactive auth flow..."
                     );
-                    oauth::perform_pkce_flow(
-                        &client_id,
-                        client_secret.as_deref(),
-                        &redirect,
-                        "https://mail.google.com/",
-                        &user_email,
-                    )?
+                    interactive_auth()?
                 }
             }
         } else {
             // no cached token, fallback as below
             if let Some(rt) = refresh_token.clone() {
-                println!("No cached access token; refreshing with refresh token...");
-                match oauth::refresh_access_token(&client_id, client_secret.as_deref(), &rt) {
+                info!("No cached access token; refreshing with refresh token...");
+                match oauth::refresh_access_token(
+                    provider,
+                    &client_id,
+                    client_secret.as_deref(),
+                    &rt,
+                ) {
                     Ok(t) => t,
                     Err(e) => {
-                        eprintln!("Refresh failed: {}, falling back to interactive auth", e);
-                        oauth::perform_pkce_flow(
-                            &client_id,
-                            client_secret.as_deref(),
-                            &redirect,
-                            "https://mail.google.com/",
-                            &user_email,
-                        )?
+                        warn!("Refresh failed: {}, falling back to interactive auth", e);
+                        interactive_auth()?
                     }
                 }
             } else {
-                println!(
-                    "No cached access token or refresh token; running interactive PKCE auth flow..."
-                );
-                oauth::perform_pkce_flow(
-                    &client_id,
-                    client_secret.as_deref(),
-                    &redirect,
-                    "https://mail.google.com/",
-                    &user_email,
-                )?
+                info!("No cached access token or refresh token; running interactive auth flow...");
+                interactive_auth()?
             }
         }
     } else {
         // no cached file
         if let Some(rt) = refresh_token.clone() {
-            println!("No cached tokens; refreshing with refresh token...");
-            match oauth::refresh_access_token(&client_id, client_secret.as_deref(), &rt) {
+            info!("No cached tokens; refreshing with refresh token...");
+            match oauth::refresh_access_token(provider, &client_id, client_secret.as_deref(), &rt) {
                 Ok(t) => t,
                 Err(e) => {
-                    eprintln!("Refresh failed: {}, falling back to interactive auth", e);
-                    oauth::perform_pkce_flow(
-                        &client_id,
-                        client_secret.as_deref(),
-                        &redirect,
-                        "https://mail.google.com/",
-                        &user_email,
-                    )?
+                    warn!("Refresh failed: {}, falling back to interactive auth", e);
+                    interactive_auth()?
                 }
             }
         } else {
-            println!(
-                "No cached tokens and no refresh token; running interactive PKCE auth flow..."
-            );
-            oauth::perform_pkce_flow(
-                &client_id,
+            info!("No cached tokens and no refresh token; running interactive auth flow...");
+            interactive_auth()?
+        }
+    };
+
+    persist_tokens(&user_email, &tokens);
+
+    // If IMAP rejects the access token (e.g. revoked server-side), refresh it once and retry
+    let force_refresh = || -> Result<oauth::Tokens> {
+        let rt = tokens
+            .refresh_token
+            .clone()
+            .or_else(|| refresh_token.clone())
+            .ok_or_else(|| {
+                anyhow!("IMAP rejected the access token and no refresh token is available")
+            })?;
+        let fresh =
+            oauth::refresh_access_token(provider, &client_id, client_secret.as_deref(), &rt)?;
+        persist_tokens(&user_email, &fresh);
+        Ok(fresh)
+    };
+    let imap = ImapClient::new(&imap_server, imap_port, use_starttls, &user_email)
+        .with_timeout(imap_timeout);
+    run_commands(
+        &args,
+        &imap,
+        &mailbox,
+        page_size,
+        tokens.access_token.clone(),
+        force_refresh,
+        keymap,
+    )
+}
+
+/// Run the IMAP work selected on the command line, then the TUI.
+/// `access_token` is the OAuth access token (or the password with password auth).
+fn run_commands(
+    args: &[String],
+    imap: &ImapClient,
+    mailbox: &str,
+    page_size: u32,
+    mut access_token: String,
+    force_refresh: impl Fn() -> Result<oauth::Tokens>,
+    keymap: KeyMap,
+) -> Result<()> {
+    // CLI utility: list available mailboxes and exit
+    // Usage: rs_mail_client --list-mailboxes
+    if args.iter().any(|a| a == "--list-mailboxes") {
+        let names = with_auth_retry(&mut access_token, force_refresh, |at| {
+            imap.list_mailboxes(at)
+        })?;
+        for name in names {
+            println!("{}", name);
+        }
+        return Ok(());
+    }
+
+    // CLI utility: print server capabilities and flag extensions we'd want but lack
+    // Usage: rs_mail_client --capabilities
+    if args.iter().any(|a| a == "--capabilities") {
+        let caps = with_auth_retry(&mut access_token, force_refresh, |at| imap.capabilities(at))?;
+        for cap in &caps {
+            println!("{}", cap);
+        }
+        for wanted in ["IDLE", "MOVE", "CONDSTORE"] {
+            if !imap.has_capability(wanted) {
+                println!("(server does not support {})", wanted);
+            }
+        }
+        return Ok(());
+    }
+
+    // CLI utility: server-side search of the selected mailbox
+    // Usage: rs_mail_client --search "from:alice subject:invoice since:1-Jan-2024"
+    if let Some(pos) = args.iter().position(|a| a == "--search") {
+        let query = args.get(pos + 1).map(String::as_str).unwrap_or("");
+        let criteria = imapsession::build_search_criteria(query)?;
+        let results = with_auth_retry(&mut access_token, force_refresh, |at| {
+            imap.search(at, mailbox, &criteria)
+        })?;
+        println!("{} message(s) match {}", results.len(), criteria);
+        for (uid, subject) in results {
+            println!("{:>8}  {}", uid, subject);
+        }
+        return Ok(());
+    }
+
+    // Authenticate to IMAP and list the most recent subjects
+    with_auth_retry(&mut access_token, force_refresh, |at| {
+        imap.list_recent_subjects(at, mailbox, page_size)
+    })?;
+
+    let _ = run_terminal(keymap);
+    Ok(())
+}
+
+/// Revoke the stored refresh token at the provider, then delete it, the cached access
+/// token, any stored IMAP password and the tokens file. Local cleanup happens even if revocation fails, and the
+/// error then says so, since the token may still be valid at the provider.
+fn logout(provider: oauth::Provider, client_id: &str, user_email: &str) -> Result<()> {
+    // revoke before deleting: once the keyring entry is gone there's nothing left to revoke
+    let revoked = match token_store::load_refresh_token(user_email)? {
+        Some(rt) => {
+            let client_secret = token_store::load_client_secret(client_id)?
+                .or_else(|| std::env::var("OAUTH_CLIENT_SECRET").ok());
+            Some(oauth::revoke_refresh_token(
+                provider,
+                client_id,
                 client_secret.as_deref(),
-                &redirect,
-                "https://mail.google.com/",
-                &user_email,
-            )?
+                &rt,
+            ))
         }
+        None => None,
     };
 
+    token_store::delete_refresh_token(user_email)?;
+    token_store::delete_access_token(user_email)?;
+    token_store::delete_password(user_email)?;
+    tokens_file::remove_tokens(user_email)?;
+    println!("Removed stored credentials for {}", user_email);
+
+    match revoked {
+        None => println!("No refresh token was stored; nothing to revoke"),
+        Some(Ok(true)) => println!("Refresh token revoked"),
+        Some(Ok(false)) => println!(
+            "{:?} has no token revocation endpoint, so the refresh token was only deleted \
+             locally and is still valid there. Remove the app's access from your account's \
+             security settings to revoke it.",
+            provider
+        ),
+        Some(Err(e)) => {
+            return Err(anyhow!(
+                "local tokens were removed, but revoking the refresh token failed: {}. \
+                 Remove the app's access from your account's security settings to be sure.",
+                e
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Persist refresh token (keyring), access token (keyring) and expiry (tokens file), best-effort
+fn persist_tokens(user_email: &str, tokens: &oauth::Tokens) {
     // Persist refresh token into keyring (best-effort; don't fail the flow if this fails)
     if let Some(ref_tok) = &tokens.refresh_token {
-        if let Err(e) = token_store::save_refresh_token(&user_email, ref_tok) {
-            eprintln!("Warning: couldn't save refresh token to keyring: {}", e);
+        if let Err(e) = token_store::save_refresh_token(user_email, ref_tok) {
+            warn!("Couldn't save refresh token to keyring: {}", e);
         } else {
-            println!("Saved refresh token into keyring for user {}", user_email);
+            info!("Saved refresh token into keyring for user {}", user_email);
         }
     }
 
-    // Persist access token + expiry (non-secret metadata) into tokens file if available
+    // Persist access token into keyring + expiry (non-secret metadata) into tokens file if available
     if let Some(expires_in) = tokens.expires_in {
         let now_s = SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .expect("system time before unix epoch")
             .as_secs();
         let expiry_epoch = (now_s + expires_in) as i64;
-        if let Err(e) = tokens_file::save_tokens(Some(&tokens.access_token), Some(expiry_epoch)) {
-            eprintln!("Warning: couldn't save tokens metadata: {}", e);
+        if let Err(e) = token_store::save_access_token(user_email, &tokens.access_token) {
+            warn!("Couldn't save access token to keyring: {}", e);
+        } else if let Err(e) = tokens_file::save_tokens(user_email, Some(expiry_epoch)) {
+            warn!("Couldn't save tokens metadata: {}", e);
         } else {
-            println!("Saved token expiry epoch {}", expiry_epoch);
+            debug!("Saved token expiry epoch {}", expiry_epoch);
         }
     } else {
         // clear stored token metadata if provider didn't return expires_in
-        let _ = tokens_file::save_tokens(None, None);
+        let _ = tokens_file::save_tokens(user_email, None);
     }
+}
 
-    // Use the access token to authenticate to IMAP via XOAUTH2
-    imapsession::list_recent_subjects(&imap_server, &user_email, &tokens.access_token)?;
+/// Run an IMAP operation with the current access token. If the server rejects the
+/// credentials, force a token refresh and retry exactly once; network/TLS errors are
+/// returned as-is since a new token wouldn't help.
+fn with_auth_retry<T>(
+    access_token: &mut String,
+    force_refresh: impl Fn() -> Result<oauth::Tokens>,
+    op: impl Fn(&str) -> Result<T>,
+) -> Result<T> {
+    match op(access_token) {
+        Err(e) if imapsession::is_auth_error(&e) => {
+            warn!(
+                "IMAP authentication failed ({}); refreshing token and retrying once",
+                e
+            );
+            *access_token = force_refresh()?.access_token;
+            op(access_token)
+        }
+        result => result,
+    }
+}
+
+/// Set up `log` output: RUST_LOG is honored (default "warn"), records are timestamped,
+/// and `--log-file <path>` appends them to a file instead of stderr.
+fn init_logging(args: &[String]) -> Result<()> {
+    let mut builder =
+        env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn"));
+    builder.format_timestamp_secs();
+
+    if let Some(pos) = args.iter().position(|a| a == "--log-file") {
+        let path = args
+            .get(pos + 1)
+            .ok_or_else(|| anyhow!("Usage: --log-file <path>"))?;
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| anyhow!("Couldn't open log file {}: {}", path, e))?;
+        builder.target(env_logger::Target::Pipe(Box::new(file)));
+    }
 
-    let _ = run_terminal();
+    builder.init();
     Ok(())
 }
@@ -1,11 +1,14 @@
 use anyhow::{Result, anyhow};
+use log::{error, warn};
 use oauth2::TokenResponse;
 use oauth2::basic::BasicClient;
 use oauth2::reqwest::http_client;
 use oauth2::{
-    AuthUrl, AuthorizationCode, ClientId, ClientSecret, PkceCodeChallenge, RedirectUrl,
-    RefreshToken, Scope, TokenUrl,
+    AuthUrl, AuthorizationCode, ClientId, ClientSecret, DeviceAuthorizationUrl, PkceCodeChallenge,
+    RedirectUrl, RefreshToken, RevocationUrl, Scope, StandardDeviceAuthorizationResponse,
+    StandardRevocableToken, TokenUrl,
 };
+use serde::{Deserialize, Serialize};
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 use std::time::{Duration, Instant};
 use tiny_http::{Response, Server};
@@ -13,6 +16,87 @@ use url::Url;
 
 use crate::token_store;
 
+/// OAuth identity provider; decides the endpoints, default scope and IMAP host
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Provider {
+    #[default]
+    Gmail,
+    Microsoft,
+}
+
+impl Provider {
+    pub fn auth_url(&self) -> &'static str {
+        match self {
+            Provider::Gmail => "https://accounts.google.com/o/oauth2/v2/auth",
+            Provider::Microsoft => "https://login.microsoftonline.com/common/oauth2/v2.0/authorize",
+        }
+    }
+
+    pub fn token_url(&self) -> &'static str {
+        match self {
+            Provider::Gmail => "https://oauth2.googleapis.com/token",
+            Provider::Microsoft => "https://login.microsoftonline.com/common/oauth2/v2.0/token",
+        }
+    }
+
+    /// Device authorization endpoint (RFC 8628), used by the device-code flow
+    pub fn device_auth_url(&self) -> &'static str {
+        match self {
+            Provider::Gmail => "https://oauth2.googleapis.com/device/code",
+            Provider::Microsoft => {
+                "https://login.microsoftonline.com/common/oauth2/v2.0/devicecode"
+            }
+        }
+    }
+
+    /// Token revocation endpoint (RFC 7009); Microsoft doesn't offer one
+    pub fn revocation_url(&self) -> Option<&'static str> {
+        match self {
+            Provider::Gmail => Some("https://oauth2.googleapis.com/revoke"),
+            Provider::Microsoft => None,
+        }
+    }
+
+    /// Space-separated scopes requested by default.
+    /// Microsoft only issues a refresh token when `offline_access` is requested.
+    pub fn default_scope(&self) -> &'static str {
+        match self {
+            Provider::Gmail => "https://mail.google.com/",
+            Provider::Microsoft => {
+                "https://outlook.office365.com/IMAP.AccessAsUser.All offline_access"
+            }
+        }
+    }
+
+    /// Scope the IMAP server insists on for XOAUTH2; tokens without it are refused at login
+    pub fn imap_scope(&self) -> &'static str {
+        match self {
+            Provider::Gmail => "https://mail.google.com/",
+            Provider::Microsoft => "https://outlook.office365.com/IMAP.AccessAsUser.All",
+        }
+    }
+
+    /// A scope in `scope` (space-separated) that this provider refuses in the
+    /// device-code flow, if any. Google rejects full mail access there.
+    pub fn device_code_blocks(&self, scope: &str) -> Option<&'static str> {
+        match self {
+            Provider::Gmail => scope
+                .split_whitespace()
+                .any(|s| s == "https://mail.google.com/")
+                .then_some("https://mail.google.com/"),
+            Provider::Microsoft => None,
+        }
+    }
+
+    pub fn default_imap_server(&self) -> &'static str {
+        match self {
+            Provider::Gmail => "imap.gmail.com",
+            Provider::Microsoft => "outlook.office365.com",
+        }
+    }
+}
+
 /// Tokens returned by the oauth flow (in-memory)
 pub struct Tokens {
     pub access_token: String,
@@ -22,6 +106,7 @@ pub struct Tokens {
 
 /// Exchange a refresh token for a new access token using the oauth2 crate
 pub fn refresh_access_token(
+    provider: Provider,
     client_id: &str,
     client_secret: Option<&str>,
     refresh_token: &str,
@@ -29,8 +114,8 @@ pub fn refresh_access_token(
     let client_id = ClientId::new(client_id.to_string());
     let client_secret = client_secret.map(|s| ClientSecret::new(s.to_string()));
 
-    let auth_url = AuthUrl::new("https://accounts.google.com/o/oauth2/v2/auth".to_string())?;
-    let token_url = TokenUrl::new("https://oauth2.googleapis.com/token".to_string())?;
+    let auth_url = AuthUrl::new(provider.auth_url().to_string())?;
+    let token_url = TokenUrl::new(provider.token_url().to_string())?;
 
     let oauth_client = BasicClient::new(client_id, client_secret, auth_url, Some(token_url));
 
@@ -50,8 +135,38 @@ pub fn refresh_access_token(
     })
 }
 
+/// Revoke a refresh token at the provider so it can't be used anymore, even if a copy
+/// survives somewhere. Returns `Ok(false)` for providers without a revocation endpoint.
+pub fn revoke_refresh_token(
+    provider: Provider,
+    client_id: &str,
+    client_secret: Option<&str>,
+    refresh_token: &str,
+) -> Result<bool> {
+    let Some(revocation_url) = provider.revocation_url() else {
+        return Ok(false);
+    };
+
+    let client_id = ClientId::new(client_id.to_string());
+    let client_secret = client_secret.map(|s| ClientSecret::new(s.to_string()));
+
+    let auth_url = AuthUrl::new(provider.auth_url().to_string())?;
+    let token_url = TokenUrl::new(provider.token_url().to_string())?;
+
+    let oauth_client = BasicClient::new(client_id, client_secret, auth_url, Some(token_url))
+        .set_revocation_uri(RevocationUrl::new(revocation_url.to_string())?);
+
+    let rt = RefreshToken::new(refresh_token.to_string());
+    oauth_client
+        .revoke_token(StandardRevocableToken::RefreshToken(rt))?
+        .request(http_client)?;
+    Ok(true)
+}
+
 /// Perform Authorization Code + PKCE flow. Opens system browser and captures code via tiny server.
+/// `scope` may hold several space-separated scopes.
 pub fn perform_pkce_flow(
+    provider: Provider,
     client_id: &str,
     client_secret: Option<&str>,
     redirect_uri: &str,
@@ -61,8 +176,8 @@ pub fn perform_pkce_flow(
     let client_id = ClientId::new(client_id.to_string());
     let client_secret = client_secret.map(|s| ClientSecret::new(s.to_string()));
 
-    let auth_url = AuthUrl::new("https://accounts.google.com/o/oauth2/v2/auth".to_string())?;
-    let token_url = TokenUrl::new("https://oauth2.googleapis.com/token".to_string())?;
+    let auth_url = AuthUrl::new(provider.auth_url().to_string())?;
+    let token_url = TokenUrl::new(provider.token_url().to_string())?;
 
     // Parse redirect_uri so bind address matches exactly
     let redirect = Url::parse(redirect_uri)
@@ -100,14 +215,14 @@ pub fn perform_pkce_flow(
 
     let (auth_url, _csrf_token) = oauth_client
         .authorize_url(oauth2::CsrfToken::new_random)
-        .add_scope(Scope::new(scope.to_string()))
+        .add_scopes(scope.split_whitespace().map(|s| Scope::new(s.to_string())))
         .set_pkce_challenge(pkce_challenge)
         .url();
 
     println!("Open this URL in your browser:\n{auth_url}");
     // best-effort: don't fail if browser can't be opened
     if let Err(e) = open::that(auth_url.as_str()) {
-        eprintln!("Warning: could not open browser automatically: {e}");
+        warn!("Could not open browser automatically: {e}");
     }
 
     // 3) Wait for callback
@@ -162,8 +277,8 @@ pub fn perform_pkce_flow(
     {
         Ok(tok) => tok,
         Err(err) => {
-            eprintln!("Token exchange failed: {:#?}", err);
-            return Err(anyhow!("Token exchange failed: see stderr for details"));
+            error!("Token exchange failed: {:#?}", err);
+            return Err(anyhow!("Token exchange failed: see log for details"));
         }
     };
 
@@ -174,7 +289,74 @@ pub fn perform_pkce_flow(
     if let Some(ref_token) = &refresh
         && let Err(e) = token_store::save_refresh_token(user_email, ref_token)
     {
-        eprintln!("Warning: could not store refresh token in keyring: {e}");
+        warn!("Could not store refresh token in keyring: {e}");
+    }
+
+    Ok(Tokens {
+        access_token: access,
+        refresh_token: refresh,
+        expires_in: expires,
+    })
+}
+
+/// Perform the OAuth device-code flow (RFC 8628) for machines without a local browser.
+/// Prints a verification URL + user code, then polls the token endpoint until the user
+/// authorizes on another device or the code expires.
+///
+/// Google only allows a limited set of scopes for device-code clients ("TVs and Limited
+/// Input devices"), which excludes the full-mail scope Gmail IMAP needs; that
+/// combination is refused up front (see `Provider::device_code_blocks`).
+pub fn perform_device_code_flow(
+    provider: Provider,
+    client_id: &str,
+    client_secret: Option<&str>,
+    scope: &str,
+    user_email: &str,
+) -> Result<Tokens> {
+    if let Some(blocked) = provider.device_code_blocks(scope) {
+        return Err(anyhow!(
+            "Google's device-code flow doesn't allow the {} scope that Gmail IMAP needs; \
+             use the browser (PKCE) flow instead: drop --device-code / set device_code = false",
+            blocked
+        ));
+    }
+
+    let client_id = ClientId::new(client_id.to_string());
+    let client_secret = client_secret.map(|s| ClientSecret::new(s.to_string()));
+
+    let auth_url = AuthUrl::new(provider.auth_url().to_string())?;
+    let token_url = TokenUrl::new(provider.token_url().to_string())?;
+    let device_url = DeviceAuthorizationUrl::new(provider.device_auth_url().to_string())?;
+
+    let oauth_client = BasicClient::new(client_id, client_secret, auth_url, Some(token_url))
+        .set_device_authorization_url(device_url);
+
+    let details: StandardDeviceAuthorizationResponse = oauth_client
+        .exchange_device_code()?
+        .add_scopes(scope.split_whitespace().map(|s| Scope::new(s.to_string())))
+        .request(http_client)
+        .map_err(|e| anyhow!("Device authorization request failed: {e}"))?;
+
+    println!(
+        "On any device, open {} and enter the code: {}",
+        details.verification_uri().as_str(),
+        details.user_code().secret()
+    );
+
+    // Polls at the interval requested by the server, honoring slow_down responses
+    let token = oauth_client
+        .exchange_device_access_token(&details)
+        .request(http_client, std::thread::sleep, Some(details.expires_in()))
+        .map_err(|e| anyhow!("Device code authorization failed: {e}"))?;
+
+    let access = token.access_token().secret().to_string();
+    let refresh = token.refresh_token().map(|r| r.secret().to_string());
+    let expires = token.expires_in().map(|d| d.as_secs());
+
+    if let Some(ref_token) = &refresh
+        && let Err(e) = token_store::save_refresh_token(user_email, ref_token)
+    {
+        warn!("Could not store refresh token in keyring: {e}");
     }
 
     Ok(Tokens {
@@ -0,0 +1,307 @@
+use anyhow::{Result, anyhow};
+use keyring::{Entry, Error as KeyringError};
+use ring::aead::{Aad, CHACHA20_POLY1305, LessSafeKey, NONCE_LEN, Nonce, UnboundKey};
+use ring::pbkdf2;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::num::NonZeroU32;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+const SERVICE: &str = "rs_mail_client";
+
+/// Environment variable holding the passphrase for the encrypted file store
+pub const PASSPHRASE_ENV: &str = "RS_MAIL_CLIENT_PASSPHRASE";
+
+/// Where secrets (tokens, passwords, client secrets) are kept
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SecretBackend {
+    /// the OS keyring (Secret Service, macOS Keychain, Windows Credential Manager)
+    #[default]
+    Keyring,
+    /// an encrypted file in the config dir, for machines without a keyring
+    File,
+}
+
+/// Key/value storage for secrets; `token_store` picks the keys
+pub trait SecretStore: Send + Sync {
+    fn get(&self, key: &str) -> Result<Option<String>>;
+    fn set(&self, key: &str, value: &str) -> Result<()>;
+    /// Remove `key`; a missing entry is not an error
+    fn delete(&self, key: &str) -> Result<()>;
+}
+
+/// Secrets in the OS keyring, one entry per key under the `rs_mail_client` service
+pub struct KeyringStore;
+
+impl SecretStore for KeyringStore {
+    fn get(&self, key: &str) -> Result<Option<String>> {
+        match Entry::new(SERVICE, key)?.get_password() {
+            Ok(v) => Ok(Some(v)),
+            Err(KeyringError::NoEntry) => Ok(None),
+            Err(e) => Err(keyring_error(e)),
+        }
+    }
+
+    fn set(&self, key: &str, value: &str) -> Result<()> {
+        Entry::new(SERVICE, key)?
+            .set_password(value)
+            .map_err(keyring_error)
+    }
+
+    fn delete(&self, key: &str) -> Result<()> {
+        match Entry::new(SERVICE, key)?.delete_credential() {
+            Ok(()) | Err(KeyringError::NoEntry) => Ok(()),
+            Err(e) => Err(keyring_error(e)),
+        }
+    }
+}
+
+/// Turn "no keyring backend" failures into something the user can act on
+fn keyring_error(e: KeyringError) -> anyhow::Error {
+    match e {
+        KeyringError::PlatformFailure(_) | KeyringError::NoStorageAccess(_) => anyhow!(
+            "the OS keyring is not available ({}). Start a Secret Service provider \
+             (e.g. gnome-keyring) or set secret_store = \"file\" in the config and export {} \
+             to keep secrets in an encrypted file instead.",
+            e,
+            PASSPHRASE_ENV
+        ),
+        e => anyhow!(e.to_string()),
+    }
+}
+
+const SALT_LEN: usize = 16;
+const PBKDF2_ITERATIONS: u32 = 200_000;
+
+/// Secrets in `secrets.enc` in the config dir: a JSON map encrypted with
+/// ChaCha20-Poly1305 under a key derived (PBKDF2-SHA256) from a passphrase (the app
+/// reads it from `RS_MAIL_CLIENT_PASSPHRASE`). Layout: salt | nonce | ciphertext+tag.
+pub struct FileStore {
+    path: PathBuf,
+    passphrase: String,
+    /// (salt, derived key), derived once per run since PBKDF2 is deliberately slow
+    key: OnceLock<([u8; SALT_LEN], [u8; 32])>,
+}
+
+impl FileStore {
+    pub fn new(path: PathBuf, passphrase: String) -> Self {
+        FileStore {
+            path,
+            passphrase,
+            key: OnceLock::new(),
+        }
+    }
+
+    fn key_for(&self, salt: Option<[u8; SALT_LEN]>) -> Result<([u8; SALT_LEN], [u8; 32])> {
+        if let Some(cached) = self.key.get()
+            && salt.is_none_or(|s| s == cached.0)
+        {
+            return Ok(*cached);
+        }
+        let salt = match salt {
+            Some(s) => s,
+            None => {
+                let mut s = [0u8; SALT_LEN];
+                SystemRandom::new()
+                    .fill(&mut s)
+                    .map_err(|_| anyhow!("failed to generate a random salt"))?;
+                s
+            }
+        };
+        let mut key = [0u8; 32];
+        pbkdf2::derive(
+            pbkdf2::PBKDF2_HMAC_SHA256,
+            NonZeroU32::new(PBKDF2_ITERATIONS).unwrap(),
+            &salt,
+            self.passphrase.as_bytes(),
+            &mut key,
+        );
+        // keep the first derivation; a file rewritten with another salt just isn't cached
+        let _ = self.key.set((salt, key));
+        Ok((salt, key))
+    }
+
+    fn read_all(&self) -> Result<HashMap<String, String>> {
+        if !self.path.exists() {
+            return Ok(HashMap::new());
+        }
+        let data = fs::read(&self.path)?;
+        if data.len() < SALT_LEN + NONCE_LEN {
+            return Err(anyhow!("{} is truncated", self.path.display()));
+        }
+        let (salt, rest) = data.split_at(SALT_LEN);
+        let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+        let (_, key) = self.key_for(Some(salt.try_into()?))?;
+
+        let key = LessSafeKey::new(
+            UnboundKey::new(&CHACHA20_POLY1305, &key).map_err(|_| anyhow!("bad key length"))?,
+        );
+        let mut in_out = ciphertext.to_vec();
+        let plain = key
+            .open_in_place(
+                Nonce::try_assume_unique_for_key(nonce).map_err(|_| anyhow!("bad nonce"))?,
+                Aad::empty(),
+                &mut in_out,
+            )
+            .map_err(|_| {
+                anyhow!(
+                    "cannot decrypt {}: wrong passphrase ({}) or the file is corrupted",
+                    self.path.display(),
+                    PASSPHRASE_ENV
+                )
+            })?;
+        Ok(serde_json::from_slice(plain)?)
+    }
+
+    fn write_all(&self, secrets: &HashMap<String, String>) -> Result<()> {
+        let (salt, key) = self.key_for(None)?;
+        let key = LessSafeKey::new(
+            UnboundKey::new(&CHACHA20_POLY1305, &key).map_err(|_| anyhow!("bad key length"))?,
+        );
+        // fresh nonce for every write; reusing one with the same key would leak plaintext
+        let mut nonce = [0u8; NONCE_LEN];
+        SystemRandom::new()
+            .fill(&mut nonce)
+            .map_err(|_| anyhow!("failed to generate a random nonce"))?;
+
+        let mut in_out = serde_json::to_vec(secrets)?;
+        key.seal_in_place_append_tag(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::empty(),
+            &mut in_out,
+        )
+        .map_err(|_| anyhow!("failed to encrypt secrets"))?;
+
+        let mut data = Vec::with_capacity(SALT_LEN + NONCE_LEN + in_out.len());
+        data.extend_from_slice(&salt);
+        data.extend_from_slice(&nonce);
+        data.extend_from_slice(&in_out);
+        write_private(&self.path, &data)
+    }
+}
+
+impl SecretStore for FileStore {
+    fn get(&self, key: &str) -> Result<Option<String>> {
+        Ok(self.read_all()?.remove(key))
+    }
+
+    fn set(&self, key: &str, value: &str) -> Result<()> {
+        let mut secrets = self.read_all()?;
+        secrets.insert(key.to_string(), value.to_string());
+        self.write_all(&secrets)
+    }
+
+    fn delete(&self, key: &str) -> Result<()> {
+        let mut secrets = self.read_all()?;
+        if secrets.remove(key).is_some() {
+            self.write_all(&secrets)?;
+        }
+        Ok(())
+    }
+}
+
+/// Write `data` readable by the current user only
+fn write_private(path: &PathBuf, data: &[u8]) -> Result<()> {
+    #[cfg(unix)]
+    {
+        use std::io::Write;
+        use std::os::unix::fs::OpenOptionsExt;
+        let mut f = fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(path)?;
+        f.write_all(data)?;
+        Ok(())
+    }
+    #[cfg(not(unix))]
+    {
+        fs::write(path, data)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        let p = std::env::temp_dir().join(format!(
+            "rs_mail_client-{}-{}.enc",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_file(&p);
+        p
+    }
+
+    #[test]
+    fn set_get_round_trip() {
+        let path = temp_path("round-trip");
+        let store = FileStore::new(path.clone(), "pw".to_string());
+        assert_eq!(store.get("a").unwrap(), None);
+        store.set("a", "1").unwrap();
+        store.set("b", "2").unwrap();
+        assert_eq!(store.get("a").unwrap().as_deref(), Some("1"));
+        assert_eq!(store.get("b").unwrap().as_deref(), Some("2"));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn delete_removes_only_that_key() {
+        let path = temp_path("delete");
+        let store = FileStore::new(path.clone(), "pw".to_string());
+        store.set("a", "1").unwrap();
+        store.set("b", "2").unwrap();
+        store.delete("a").unwrap();
+        store.delete("missing").unwrap();
+        assert_eq!(store.get("a").unwrap(), None);
+        assert_eq!(store.get("b").unwrap().as_deref(), Some("2"));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn another_instance_reads_the_file_with_its_salt() {
+        let path = temp_path("reopen");
+        FileStore::new(path.clone(), "pw".to_string())
+            .set("a", "1")
+            .unwrap();
+        let reopened = FileStore::new(path.clone(), "pw".to_string());
+        assert_eq!(reopened.get("a").unwrap().as_deref(), Some("1"));
+        // and writes through it stay readable by a third one
+        reopened.set("b", "2").unwrap();
+        let third = FileStore::new(path.clone(), "pw".to_string());
+        assert_eq!(third.get("a").unwrap().as_deref(), Some("1"));
+        assert_eq!(third.get("b").unwrap().as_deref(), Some("2"));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn wrong_passphrase_cannot_decrypt() {
+        let path = temp_path("wrong-pass");
+        FileStore::new(path.clone(), "pw".to_string())
+            .set("a", "1")
+            .unwrap();
+        let err = FileStore::new(path.clone(), "nope".to_string())
+            .get("a")
+            .unwrap_err();
+        assert!(err.to_string().contains("cannot decrypt"));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn truncated_file_is_rejected() {
+        let path = temp_path("truncated");
+        fs::write(&path, [0u8; SALT_LEN + NONCE_LEN - 1]).unwrap();
+        let err = FileStore::new(path.clone(), "pw".to_string())
+            .get("a")
+            .unwrap_err();
+        assert!(err.to_string().contains("truncated"));
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,222 @@
+use anyhow::{Result, anyhow};
+use ratatui::crossterm::event::KeyCode;
+use std::collections::HashMap;
+
+/// Actions that can be bound to a key in the `[keys]` config section
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    Quit,
+    Add,
+    Delete,
+    MoveDown,
+    MoveUp,
+    Help,
+}
+
+impl Action {
+    pub const ALL: [Action; 6] = [
+        Action::Quit,
+        Action::Add,
+        Action::Delete,
+        Action::MoveDown,
+        Action::MoveUp,
+        Action::Help,
+    ];
+
+    /// Name used for this action in the config file
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::Quit => "quit",
+            Action::Add => "add",
+            Action::Delete => "delete",
+            Action::MoveDown => "move_down",
+            Action::MoveUp => "move_up",
+            Action::Help => "help",
+        }
+    }
+
+    /// Short human description shown in the help view
+    pub fn description(&self) -> &'static str {
+        match self {
+            Action::Quit => "Quit",
+            Action::Add => "Add a new item",
+            Action::Delete => "Delete the selected item",
+            Action::MoveDown => "Move selection down",
+            Action::MoveUp => "Move selection up",
+            Action::Help => "Toggle this help",
+        }
+    }
+
+    fn default_key(&self) -> KeyCode {
+        match self {
+            Action::Quit => KeyCode::Esc,
+            Action::Add => KeyCode::Char('a'),
+            Action::Delete => KeyCode::Char('d'),
+            Action::MoveDown => KeyCode::Char('j'),
+            Action::MoveUp => KeyCode::Char('k'),
+            Action::Help => KeyCode::Char('h'),
+        }
+    }
+}
+
+/// Key -> action lookup built from the config `[keys]` section plus defaults
+#[derive(Debug, Clone)]
+pub struct KeyMap {
+    bindings: HashMap<KeyCode, Action>,
+}
+
+impl Default for KeyMap {
+    fn default() -> Self {
+        KeyMap {
+            bindings: Action::ALL.iter().map(|a| (a.default_key(), *a)).collect(),
+        }
+    }
+}
+
+impl KeyMap {
+    /// Build the key map from `[keys]` (action name -> key string, e.g. `move_down = "n"`).
+    /// Actions not listed keep their default key; unknown action names, unparseable
+    /// keys and two actions sharing a key are rejected.
+    pub fn from_config(keys: Option<&HashMap<String, String>>) -> Result<KeyMap> {
+        let mut by_action: HashMap<Action, KeyCode> =
+            Action::ALL.iter().map(|a| (*a, a.default_key())).collect();
+
+        for (name, key) in keys.into_iter().flatten() {
+            let action = Action::ALL
+                .iter()
+                .find(|a| a.name() == name)
+                .ok_or_else(|| {
+                    let known: Vec<_> = Action::ALL.iter().map(|a| a.name()).collect();
+                    anyhow!(
+                        "unknown action '{}' in [keys]; expected one of: {}",
+                        name,
+                        known.join(", ")
+                    )
+                })?;
+            let code =
+                parse_key(key).map_err(|e| anyhow!("[keys] {} = \"{}\": {}", name, key, e))?;
+            by_action.insert(*action, code);
+        }
+
+        let mut bindings = HashMap::new();
+        for action in Action::ALL {
+            let code = by_action[&action];
+            if let Some(other) = bindings.insert(code, action) {
+                return Err(anyhow!(
+                    "[keys] '{}' and '{}' are both bound to the same key",
+                    other.name(),
+                    action.name()
+                ));
+            }
+        }
+        Ok(KeyMap { bindings })
+    }
+
+    pub fn action(&self, code: KeyCode) -> Option<Action> {
+        self.bindings.get(&code).copied()
+    }
+
+    /// Display name of the key bound to `action` (e.g. "j", "esc")
+    pub fn key_label(&self, action: Action) -> String {
+        self.bindings
+            .iter()
+            .find(|(_, a)| **a == action)
+            .map(|(code, _)| key_name(*code))
+            .unwrap_or_else(|| "-".to_string())
+    }
+}
+
+/// Inverse of `parse_key`, used for display
+fn key_name(code: KeyCode) -> String {
+    match code {
+        KeyCode::Char(' ') => "space".to_string(),
+        KeyCode::Char(c) => c.to_string(),
+        KeyCode::Esc => "esc".to_string(),
+        KeyCode::Enter => "enter".to_string(),
+        KeyCode::Tab => "tab".to_string(),
+        KeyCode::Backspace => "backspace".to_string(),
+        KeyCode::Up => "up".to_string(),
+        KeyCode::Down => "down".to_string(),
+        KeyCode::Left => "left".to_string(),
+        KeyCode::Right => "right".to_string(),
+        KeyCode::Home => "home".to_string(),
+        KeyCode::End => "end".to_string(),
+        KeyCode::PageUp => "pageup".to_string(),
+        KeyCode::PageDown => "pagedown".to_string(),
+        other => format!("{:?}", other),
+    }
+}
+
+/// Parse a key string: a single character ("j", "?") or a named key
+/// ("esc", "enter", "tab", "space", "backspace", "up", "down", "left", "right",
+/// "home", "end", "pageup", "pagedown").
+fn parse_key(s: &str) -> Result<KeyCode> {
+    let mut chars = s.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Ok(KeyCode::Char(c));
+    }
+    let code = match s.to_ascii_lowercase().as_str() {
+        "esc" | "escape" => KeyCode::Esc,
+        "enter" | "return" => KeyCode::Enter,
+        "tab" => KeyCode::Tab,
+        "space" => KeyCode::Char(' '),
+        "backspace" => KeyCode::Backspace,
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        "pageup" => KeyCode::PageUp,
+        "pagedown" => KeyCode::PageDown,
+        _ => return Err(anyhow!("unrecognized key name")),
+    };
+    Ok(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(a, k)| (a.to_string(), k.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn default_map_binds_every_action() {
+        let map = KeyMap::from_config(None).unwrap();
+        for action in Action::ALL {
+            assert_eq!(map.action(action.default_key()), Some(action));
+        }
+        assert_eq!(map.key_label(Action::Quit), "esc");
+    }
+
+    #[test]
+    fn override_replaces_only_that_binding() {
+        let map = KeyMap::from_config(Some(&keys(&[("move_down", "n")]))).unwrap();
+        assert_eq!(map.action(KeyCode::Char('n')), Some(Action::MoveDown));
+        assert_eq!(map.action(KeyCode::Char('j')), None);
+        assert_eq!(map.action(KeyCode::Char('k')), Some(Action::MoveUp));
+    }
+
+    #[test]
+    fn unknown_action_is_rejected() {
+        let err = KeyMap::from_config(Some(&keys(&[("jump", "x")]))).unwrap_err();
+        assert!(err.to_string().contains("unknown action 'jump'"));
+    }
+
+    #[test]
+    fn unparseable_key_is_rejected() {
+        let err = KeyMap::from_config(Some(&keys(&[("quit", "ctrl-q")]))).unwrap_err();
+        assert!(err.to_string().contains("unrecognized key name"));
+    }
+
+    #[test]
+    fn duplicate_binding_is_rejected() {
+        let err = KeyMap::from_config(Some(&keys(&[("add", "d")]))).unwrap_err();
+        assert!(err.to_string().contains("both bound to the same key"));
+    }
+}
@@ -1,46 +1,81 @@
+pub mod keymap;
 pub mod structs;
-use color_eyre::eyre::{Ok, Result};
-use ratatui::crossterm::event::KeyEvent;
+use color_eyre::eyre::Result;
+use ratatui::crossterm::event::{
+    DisableMouseCapture, EnableMouseCapture, KeyEvent, MouseButton, MouseEvent, MouseEventKind,
+};
+use ratatui::crossterm::execute;
+use ratatui::layout::Rect;
 use ratatui::prelude::Stylize;
 use ratatui::style::Style;
-use ratatui::text::ToSpan;
+use ratatui::text::{Line, ToSpan};
 use ratatui::widgets::{ListState, Padding, Paragraph};
 use ratatui::{
     DefaultTerminal, Frame,
     crossterm::event::{self, Event},
-    layout::{Constraint, Layout},
+    layout::{Constraint, Flex, Layout},
     style::Color,
     widgets::{Block, BorderType, List, ListItem, Widget},
 };
 
+use keymap::{Action, KeyMap};
 use structs::{AppState, FormAction, TodoItem};
 
-pub fn run_terminal() -> Result<()> {
+pub fn run_terminal(keymap: KeyMap) -> Result<()> {
     let mut state = AppState {
         is_add_new: false,
+        is_help: false,
         list_state: ListState::default(),
         items: Vec::<TodoItem>::default(),
         input_value: String::default(),
+        keymap,
+        list_area: Rect::default(),
     };
     state.is_add_new = false;
 
     color_eyre::install()?;
 
     let terminal = ratatui::init();
-    let result = run(terminal, &mut state);
+    // from here on every exit path (errors from `?` included) goes through the guard
+    let _guard = TerminalGuard;
+    execute!(std::io::stdout(), EnableMouseCapture)?;
+    run(terminal, &mut state)
+}
 
-    ratatui::restore();
+/// Puts the terminal back (mouse capture off, raw mode off, main screen) when dropped
+struct TerminalGuard;
 
-    result
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        // disable mouse capture before leaving the alternate screen
+        let _ = execute!(std::io::stdout(), DisableMouseCapture);
+        ratatui::restore();
+    }
 }
 
 fn run(mut terminal: DefaultTerminal, app_state: &mut AppState) -> Result<()> {
     loop {
         terminal.draw(|f| render(f, app_state))?;
-        let Event::Key(key) = event::read()? else {
-            continue;
+        let key = match event::read()? {
+            Event::Key(key) => key,
+            Event::Mouse(mouse) => {
+                if !app_state.is_add_new && !app_state.is_help {
+                    handle_mouse(mouse, app_state);
+                }
+                continue;
+            }
+            Event::Resize(_, _) => {
+                // some terminals leave stale cells behind on resize: force a full repaint,
+                // and drop the old list geometry until the next draw records the new one
+                terminal.clear()?;
+                app_state.list_area = Rect::default();
+                continue;
+            }
+            _ => continue,
         };
-        if app_state.is_add_new {
+        if app_state.is_help {
+            handle_help(key, app_state);
+        } else if app_state.is_add_new {
             match handle_add_new(key, app_state) {
                 FormAction::None => {}
                 FormAction::Submit => {
@@ -84,34 +119,65 @@ fn handle_add_new(key: KeyEvent, app_state: &mut AppState) -> FormAction {
     FormAction::None
 }
 
+fn handle_help(key: KeyEvent, app_state: &mut AppState) {
+    // Esc closes help even if it's bound to quit, so the user can't quit by accident
+    if key.code == event::KeyCode::Esc || app_state.keymap.action(key.code) == Some(Action::Help) {
+        app_state.is_help = false;
+    }
+}
+
 fn handle_key(key: KeyEvent, app_state: &mut AppState) -> bool {
-    match key.code {
-        event::KeyCode::Esc => {
+    match app_state.keymap.action(key.code) {
+        Some(Action::Quit) => {
             return true;
         }
-        event::KeyCode::Char(char) => match char {
-            'a' => {
-                app_state.is_add_new = true;
-            }
-
-            'd' => {
-                if let Some(index) = app_state.list_state.selected() {
-                    app_state.items.remove(index);
-                }
+        Some(Action::Add) => {
+            app_state.is_add_new = true;
+        }
+        Some(Action::Delete) => {
+            if let Some(index) = app_state.list_state.selected() {
+                app_state.items.remove(index);
             }
+        }
+        Some(Action::MoveDown) => {
+            app_state.list_state.select_next();
+        }
+        Some(Action::MoveUp) => {
+            app_state.list_state.select_previous();
+        }
+        Some(Action::Help) => {
+            app_state.is_help = true;
+        }
+        None => {}
+    }
+    false
+}
 
-            'j' => {
-                app_state.list_state.select_next();
+fn handle_mouse(mouse: MouseEvent, app_state: &mut AppState) {
+    match mouse.kind {
+        MouseEventKind::Down(MouseButton::Left) => {
+            let area = app_state.list_area;
+            let inside = mouse.column >= area.x
+                && mouse.column < area.x + area.width
+                && mouse.row >= area.y
+                && mouse.row < area.y + area.height;
+            if !inside {
+                return;
             }
-            'k' => {
-                app_state.list_state.select_previous();
+            // one row per item, shifted by how far the list is scrolled
+            let index = app_state.list_state.offset() + (mouse.row - area.y) as usize;
+            if index < app_state.items.len() {
+                app_state.list_state.select(Some(index));
             }
-            _ => {}
-        },
-
+        }
+        MouseEventKind::ScrollDown => {
+            app_state.list_state.select_next();
+        }
+        MouseEventKind::ScrollUp => {
+            app_state.list_state.select_previous();
+        }
         _ => {}
     }
-    false
 }
 
 fn render(frame: &mut Frame, app_state: &mut AppState) {
@@ -119,7 +185,9 @@ fn render(frame: &mut Frame, app_state: &mut AppState) {
         .margin(1)
         .areas(frame.area());
 
-    if app_state.is_add_new {
+    if app_state.is_help {
+        render_help(frame, border_area, &app_state.keymap);
+    } else if app_state.is_add_new {
         Paragraph::new(app_state.input_value.as_str())
             .block(
                 Block::bordered()
@@ -134,11 +202,35 @@ fn render(frame: &mut Frame, app_state: &mut AppState) {
             .margin(1)
             .areas(border_area);
 
+        let count = app_state.items.len();
         Block::bordered()
+            .title(format!(
+                " {} item{} ",
+                count,
+                if count == 1 { "" } else { "s" }
+            ))
             .border_type(BorderType::Rounded)
             .fg(Color::Yellow)
             .render(border_area, frame.buffer_mut());
 
+        app_state.list_area = inner_area;
+
+        if app_state.items.is_empty() {
+            // centered placeholder so an empty list doesn't look broken
+            let [placeholder_area] = Layout::vertical([Constraint::Length(1)])
+                .flex(Flex::Center)
+                .areas(inner_area);
+            Paragraph::new(format!(
+                "Nothing here yet — press {} to add an item, {} for help",
+                app_state.keymap.key_label(Action::Add),
+                app_state.keymap.key_label(Action::Help)
+            ))
+            .centered()
+            .fg(Color::DarkGray)
+            .render(placeholder_area, frame.buffer_mut());
+            return;
+        }
+
         let list = List::new(
             app_state
                 .items
@@ -151,3 +243,46 @@ fn render(frame: &mut Frame, app_state: &mut AppState) {
         frame.render_stateful_widget(list, inner_area, &mut app_state.list_state);
     }
 }
+
+fn render_help(frame: &mut Frame, area: Rect, keymap: &KeyMap) {
+    let binding = |action: Action| {
+        Line::from(vec![
+            format!("  {:<12}", keymap.key_label(action)).bold(),
+            action.description().into(),
+        ])
+    };
+
+    let mut lines = vec![Line::from("Global".underlined())];
+    lines.extend([Action::Help, Action::Quit].map(binding));
+    lines.push(Line::default());
+    lines.push(Line::from("List".underlined()));
+    lines.extend(
+        [
+            Action::MoveDown,
+            Action::MoveUp,
+            Action::Add,
+            Action::Delete,
+        ]
+        .map(binding),
+    );
+    lines.push(Line::default());
+    lines.push(Line::from("Input".underlined()));
+    lines.push(Line::from(vec![
+        format!("  {:<12}", "enter").bold(),
+        "Save the item".into(),
+    ]));
+    lines.push(Line::from(vec![
+        format!("  {:<12}", "esc").bold(),
+        "Cancel".into(),
+    ]));
+
+    Paragraph::new(lines)
+        .block(
+            Block::bordered()
+                .title(" Help ".to_span().into_centered_line())
+                .fg(Color::Cyan)
+                .padding(Padding::uniform(1))
+                .border_type(BorderType::Rounded),
+        )
+        .render(area, frame.buffer_mut());
+}
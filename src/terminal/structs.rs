@@ -1,11 +1,18 @@
+use ratatui::layout::Rect;
 use ratatui::widgets::{List, ListState};
 
+use super::keymap::KeyMap;
+
 #[derive(Debug, Default)]
 pub struct AppState {
     pub items: Vec<TodoItem>,
     pub list_state: ListState,
     pub is_add_new: bool,
+    pub is_help: bool,
     pub input_value: String,
+    pub keymap: KeyMap,
+    /// where the list was last drawn, to map mouse clicks to rows
+    pub list_area: Rect,
 }
 
 impl AppState {
@@ -14,7 +21,10 @@ impl AppState {
             items: Vec::<TodoItem>::default(),
             list_state: ListState::default(),
             is_add_new: false,
+            is_help: false,
             input_value: String::default(),
+            keymap: KeyMap::default(),
+            list_area: Rect::default(),
         }
     }
 }
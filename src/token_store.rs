@@ -1,42 +1,99 @@
+use crate::config;
+use crate::secret_store::{FileStore, KeyringStore, PASSPHRASE_ENV, SecretBackend, SecretStore};
 use anyhow::{Result, anyhow};
-use keyring::{Entry, Error as KeyringError};
+use std::sync::OnceLock;
 
-const SERVICE: &str = "rs_mail_client";
+static STORE: OnceLock<Box<dyn SecretStore>> = OnceLock::new();
 
-/// Save a refresh token into the OS keyring for the given username (email)
+/// Choose where secrets are kept for the rest of the run. Call before any other
+/// function here; without it the OS keyring is used.
+pub fn set_backend(backend: SecretBackend) -> Result<()> {
+    let store: Box<dyn SecretStore> = match backend {
+        SecretBackend::Keyring => Box::new(KeyringStore),
+        SecretBackend::File => {
+            let passphrase = std::env::var(PASSPHRASE_ENV).map_err(|_| {
+                anyhow!(
+                    "secret_store is \"file\" but {} is not set; export the passphrase \
+                     for the encrypted secrets file",
+                    PASSPHRASE_ENV
+                )
+            })?;
+            Box::new(FileStore::new(
+                config::config_path()?.with_file_name("secrets.enc"),
+                passphrase,
+            ))
+        }
+    };
+    STORE
+        .set(store)
+        .map_err(|_| anyhow!("secret backend already chosen"))
+}
+
+fn store() -> &'static dyn SecretStore {
+    STORE.get_or_init(|| Box::new(KeyringStore)).as_ref()
+}
+
+/// Save a refresh token into the secret store for the given username (email)
 pub fn save_refresh_token(username: &str, refresh_token: &str) -> Result<()> {
-    let entry = Entry::new(SERVICE, username);
-    entry?
-        .set_password(refresh_token)
-        .map_err(|e| anyhow!(e.to_string()))?;
-    Ok(())
+    store().set(username, refresh_token)
 }
 
-/// Load a refresh token from the keyring for the given username (email)
+/// Load a refresh token from the secret store for the given username (email)
 pub fn load_refresh_token(username: &str) -> Result<Option<String>> {
-    let entry = Entry::new(SERVICE, username);
-    match entry?.get_password() {
-        Ok(v) => Ok(Some(v)),
-        Err(KeyringError::NoEntry) => Ok(None),
-        Err(e) => Err(anyhow!(e.to_string())),
-    }
+    store().get(username)
+}
+
+/// Remove the refresh token for the given username (email); a missing entry is not an error
+pub fn delete_refresh_token(username: &str) -> Result<()> {
+    store().delete(username)
+}
+
+/// Secret store key for the cached access token, kept apart from the refresh token
+fn access_token_key(username: &str) -> String {
+    format!("access:{}", username)
+}
+
+/// Save a (short-lived) access token into the secret store for the given username (email)
+pub fn save_access_token(username: &str, access_token: &str) -> Result<()> {
+    store().set(&access_token_key(username), access_token)
+}
+
+/// Load the cached access token from the secret store for the given username (email)
+pub fn load_access_token(username: &str) -> Result<Option<String>> {
+    store().get(&access_token_key(username))
+}
+
+/// Remove the cached access token for the given username (email), if any
+pub fn delete_access_token(username: &str) -> Result<()> {
+    store().delete(&access_token_key(username))
+}
+
+/// Secret store key for an IMAP password (password auth instead of OAuth)
+fn password_key(username: &str) -> String {
+    format!("password:{}", username)
+}
+
+/// Save an IMAP login password into the secret store for the given username (email)
+pub fn save_password(username: &str, password: &str) -> Result<()> {
+    store().set(&password_key(username), password)
+}
+
+/// Load the IMAP login password from the secret store for the given username (email)
+pub fn load_password(username: &str) -> Result<Option<String>> {
+    store().get(&password_key(username))
+}
+
+/// Remove the IMAP login password for the given username (email), if any
+pub fn delete_password(username: &str) -> Result<()> {
+    store().delete(&password_key(username))
 }
 
-/// Save a client secret into the keyring, keyed by client_id
+/// Save a client secret into the secret store, keyed by client_id
 pub fn save_client_secret(client_id: &str, client_secret: &str) -> Result<()> {
-    let entry = Entry::new(SERVICE, client_id);
-    entry?
-        .set_password(client_secret)
-        .map_err(|e| anyhow!(e.to_string()))?;
-    Ok(())
+    store().set(client_id, client_secret)
 }
 
-/// Load client secret from keyring by client_id
+/// Load client secret from the secret store by client_id
 pub fn load_client_secret(client_id: &str) -> Result<Option<String>> {
-    let entry = Entry::new(SERVICE, client_id);
-    match entry?.get_password() {
-        Ok(v) => Ok(Some(v)),
-        Err(KeyringError::NoEntry) => Ok(None),
-        Err(e) => Err(anyhow!(e.to_string())),
-    }
+    store().get(client_id)
 }
@@ -1,11 +1,15 @@
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-/// Non-secret tokens metadata stored in ~/.config/rs_mail_client/tokens.json
+/// Non-secret tokens metadata stored per account in
+/// ~/.config/rs_mail_client/tokens-<email>.json
+/// (the access token itself lives in the keyring, see `token_store`)
 #[derive(Debug, Serialize, Deserialize)]
 pub struct TokensFile {
+    /// plaintext access token written by older versions; only read to migrate it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub access_token: Option<String>,
     pub expires_at_epoch: Option<i64>, // epoch seconds
 }
@@ -16,18 +20,68 @@ fn config_dir() -> Result<PathBuf> {
         .join("rs_mail_client"))
 }
 
-fn tokens_path() -> Result<PathBuf> {
-    let mut p = config_dir()?;
+fn tokens_path(username: &str) -> Result<PathBuf> {
+    let p = config_dir()?;
     fs::create_dir_all(&p)?;
-    p.push("tokens.json");
-    Ok(p)
+    Ok(p.join(tokens_file_name(username)))
 }
 
-/// Save access_token (non-secret) and expiry epoch
-pub fn save_tokens(access_token: Option<&str>, expires_at_epoch: Option<i64>) -> Result<()> {
-    let p = tokens_path()?;
+fn tokens_file_name(username: &str) -> String {
+    let safe: String = username
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || "@.-_+".contains(c) {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("tokens-{}.json", safe)
+}
+
+/// Single shared file used before accounts were supported
+const LEGACY_TOKENS_FILE: &str = "tokens.json";
+
+/// Hand the pre-accounts `tokens.json` to `owner`, the top-level `user_email` it was
+/// written for, by renaming it to that account's tokens file. Other accounts never
+/// read it, so they can't pick up (and try to log in with) another account's token.
+/// If `owner` already has its own file, the legacy one is stale and is removed.
+pub fn migrate_legacy_tokens(owner: &str) -> Result<()> {
+    let dir = config_dir()?;
+    fs::create_dir_all(&dir)?;
+    migrate_legacy_tokens_in(&dir, owner)
+}
+
+fn migrate_legacy_tokens_in(dir: &Path, owner: &str) -> Result<()> {
+    let legacy = dir.join(LEGACY_TOKENS_FILE);
+    if !legacy.exists() {
+        return Ok(());
+    }
+    let target = dir.join(tokens_file_name(owner));
+    if target.exists() {
+        fs::remove_file(legacy)?;
+    } else {
+        fs::rename(legacy, target)?;
+    }
+    Ok(())
+}
+
+/// Default safety margin (seconds) before expiry at which a cached token is refreshed
+pub const DEFAULT_REFRESH_SKEW_SECS: i64 = 60;
+
+/// True if a token expiring at `expires_at_epoch` should no longer be handed out at `now`.
+/// `skew_secs` treats tokens about to expire as already expired, so a request started
+/// just before expiry doesn't fail halfway through.
+pub fn is_expired(expires_at_epoch: i64, now: i64, skew_secs: i64) -> bool {
+    now + skew_secs >= expires_at_epoch
+}
+
+/// Save the access token expiry epoch for `username`
+pub fn save_tokens(username: &str, expires_at_epoch: Option<i64>) -> Result<()> {
+    let p = tokens_path(username)?;
     let tf = TokensFile {
-        access_token: access_token.map(|s| s.to_string()),
+        access_token: None,
         expires_at_epoch,
     };
     let s = serde_json::to_string_pretty(&tf)?;
@@ -35,13 +89,68 @@ pub fn save_tokens(access_token: Option<&str>, expires_at_epoch: Option<i64>) ->
     Ok(())
 }
 
-/// Load tokens file if present
-pub fn load_tokens() -> Result<Option<TokensFile>> {
-    let p = tokens_path()?;
+/// Load the tokens file for `username` if present
+pub fn load_tokens(username: &str) -> Result<Option<TokensFile>> {
+    load_tokens_from(&tokens_path(username)?)
+}
+
+fn load_tokens_from(p: &Path) -> Result<Option<TokensFile>> {
     if !p.exists() {
         return Ok(None);
     }
-    let s = fs::read_to_string(&p)?;
+    let s = fs::read_to_string(p)?;
     let tf: TokensFile = serde_json::from_str(&s)?;
     Ok(Some(tf))
 }
+
+/// Delete the tokens file for `username` on logout
+pub fn remove_tokens(username: &str) -> Result<()> {
+    let p = tokens_path(username)?;
+    if p.exists() {
+        fs::remove_file(p)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn token_inside_skew_window_needs_refresh() {
+        let now = 1_700_000_000;
+        assert!(is_expired(now + 30, now, DEFAULT_REFRESH_SKEW_SECS));
+    }
+
+    #[test]
+    fn token_outside_skew_window_is_reused() {
+        let now = 1_700_000_000;
+        assert!(!is_expired(now + 3600, now, DEFAULT_REFRESH_SKEW_SECS));
+        assert!(is_expired(now, now, 0));
+    }
+
+    #[test]
+    fn legacy_tokens_only_go_to_their_owner() {
+        let dir =
+            std::env::temp_dir().join(format!("rs_mail_client-legacy-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join(LEGACY_TOKENS_FILE),
+            r#"{"access_token":"token-of-a","expires_at_epoch":1700000000}"#,
+        )
+        .unwrap();
+
+        migrate_legacy_tokens_in(&dir, "a@example.com").unwrap();
+
+        let b = load_tokens_from(&dir.join(tokens_file_name("b@example.com"))).unwrap();
+        assert!(b.is_none());
+        let a = load_tokens_from(&dir.join(tokens_file_name("a@example.com")))
+            .unwrap()
+            .unwrap();
+        assert_eq!(a.access_token.as_deref(), Some("token-of-a"));
+        assert!(!dir.join(LEGACY_TOKENS_FILE).exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}